@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
 
 pub(crate) fn default_clip_dir() -> PathBuf {
     let documents_dir = dirs_next::document_dir()
@@ -10,3 +10,103 @@ pub(crate) fn default_clip_dir() -> PathBuf {
         .join("rolling-sampler-clone")
         .join("clips")
 }
+
+/// Drops leading and trailing frames that are exactly zero across every channel. A frame is
+/// kept as soon as any channel is non-zero, so genuine silence inside the clip is preserved.
+/// Returns an empty vec (per channel) when the whole clip is silent.
+pub(crate) fn trim_silence(samples: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+    let len = samples.first().map_or(0, Vec::len);
+    let is_silent = |frame: usize| samples.iter().all(|chan| chan[frame] == 0.0);
+
+    let first = (0..len).find(|&f| !is_silent(f));
+    let (start, end) = match first {
+        Some(start) => (start, (0..len).rev().find(|&f| !is_silent(f)).unwrap() + 1),
+        None => return samples.iter().map(|_| Vec::new()).collect(),
+    };
+
+    samples.into_iter().map(|chan| chan[start..end].to_vec()).collect()
+}
+
+/// Encodes `samples` (one inner vec per channel) as an interleaved 32-bit float WAV and writes
+/// it into `dir` under a timestamped filename. When `trim` is set, leading/trailing silence is
+/// removed first. A clip that is entirely silent is skipped rather than written, whether or not
+/// `trim` is set.
+pub(crate) fn save_clip(dir: &Path, samples: Vec<Vec<f32>>, sample_rate: u32, trim: bool) {
+    let samples = if trim { trim_silence(samples) } else { samples };
+
+    let channels = samples.len() as u16;
+    let frames = samples.first().map_or(0, Vec::len);
+    let silent = samples.iter().all(|chan| chan.iter().all(|&s| s == 0.0));
+    if channels == 0 || frames == 0 || silent {
+        return;
+    }
+
+    if let Err(err) = std::fs::create_dir_all(dir) {
+        nih_plug::nih_error!("Failed to create clip directory: {err}");
+        return;
+    }
+
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let path = dir.join(format!("clip-{stamp}.wav"));
+
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+
+    let write = || -> Result<(), hound::Error> {
+        let mut writer = hound::WavWriter::create(&path, spec)?;
+        for frame in 0..frames {
+            for chan in &samples {
+                writer.write_sample(chan[frame])?;
+            }
+        }
+        writer.finalize()
+    };
+
+    if let Err(err) = write() {
+        nih_plug::nih_error!("Failed to write clip {}: {err}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_leading_and_trailing_zero_runs() {
+        let input = vec![
+            vec![0.0, 0.0, 0.5, 0.0, -0.3, 0.0, 0.0],
+            vec![0.0, 0.0, 0.1, 0.0,  0.2, 0.0, 0.0],
+        ];
+        let expected = vec![vec![0.5, 0.0, -0.3], vec![0.1, 0.0, 0.2]];
+        assert_eq!(trim_silence(input), expected);
+    }
+
+    #[test]
+    fn keeps_frame_where_any_channel_is_nonzero() {
+        // The leading edge is frame 1: silent on channel 0 but not on channel 1
+        let input = vec![
+            vec![0.0, 0.0, 1.0],
+            vec![0.0, 0.4, 0.0],
+        ];
+        let expected = vec![vec![0.0, 1.0], vec![0.4, 0.0]];
+        assert_eq!(trim_silence(input), expected);
+    }
+
+    #[test]
+    fn preserves_interior_silence() {
+        let input = vec![vec![0.2, 0.0, 0.0, 0.3]];
+        assert_eq!(trim_silence(input.clone()), input);
+    }
+
+    #[test]
+    fn all_silent_returns_empty_channels() {
+        let input = vec![vec![0.0, 0.0], vec![0.0, 0.0]];
+        assert_eq!(trim_silence(input), vec![Vec::<f32>::new(), Vec::<f32>::new()]);
+    }
+}
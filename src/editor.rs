@@ -1,4 +1,4 @@
-use std::{fmt::Display, ops::RangeInclusive, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}};
+use std::{fmt::Display, ops::RangeInclusive, path::PathBuf, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}};
 
 use crossbeam::atomic::AtomicCell;
 use nih_plug::prelude::*;
@@ -9,7 +9,20 @@ use triple_buffer;
 use crate::{RollingSamplerCloneParams, buffer_size::{Note, BufferSizeUnit}};
 
 pub(crate) struct EditorState {
-    pub waveform_buffer_output: Arc<Mutex<triple_buffer::Output<Vec<f32>>>>
+    pub waveform_buffer_output: Arc<Mutex<triple_buffer::Output<Vec<f32>>>>,
+    /// Flag the audio thread polls to snapshot and save the current buffer (or the active selection)
+    pub save_requested: Arc<AtomicBool>,
+    /// Whether the stored buffer is being auditioned through the output
+    pub playing_back: Arc<AtomicBool>,
+    /// Normalized (0..1) audition position, drawn as the playhead line
+    pub playhead: Arc<AtomicF32>,
+    /// Flag the audio thread polls to clear the buffer (or the active selection)
+    pub clear_requested: Arc<AtomicBool>,
+    /// Whether a region of the buffer is selected
+    pub selection_active: Arc<AtomicBool>,
+    /// Normalized (0..1) selection bounds
+    pub selection_start: Arc<AtomicF32>,
+    pub selection_end: Arc<AtomicF32>,
 }
 
 pub(crate) struct Theme {
@@ -25,14 +38,19 @@ pub(crate) enum ThemeType {
     #[serde(rename = "dark")]
     Dark,
     #[serde(rename = "light")]
-    Light
+    Light,
+    /// Follows the system theme reported by the host/OS, resolving to `Dark` or `Light` at
+    /// runtime; the luminance of the background is not measured
+    #[serde(rename = "auto")]
+    Auto
 }
 
 impl Display for ThemeType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ThemeType::Dark => write!(f, "Dark"),
-            ThemeType::Light => write!(f, "Light")
+            ThemeType::Light => write!(f, "Light"),
+            ThemeType::Auto => write!(f, "Auto")
         }
     }
 }
@@ -46,7 +64,8 @@ impl ThemeType {
                 fg_color_secondary: Color32::from_rgb(209, 209, 209),
                 text_color: Color32::from_rgb(46, 46, 46),
             },
-            ThemeType::Dark => Theme {
+            // `Auto` is resolved to `Dark`/`Light` before this is called; fall back to dark
+            ThemeType::Dark | ThemeType::Auto => Theme {
                 bg_color: Color32::from_rgb(15, 15, 15),
                 fg_color_primary: Color32::from_rgb(31, 31, 31),
                 fg_color_secondary: Color32::from_rgb(46, 46, 46),
@@ -58,12 +77,36 @@ impl ThemeType {
     pub const fn egui_theme(self) -> egui::Theme {
         match self {
             ThemeType::Light => egui::Theme::Light,
-            ThemeType::Dark => egui::Theme::Dark
+            ThemeType::Dark | ThemeType::Auto => egui::Theme::Dark
         }
     }
+
+    /// Resolves `Auto` to a concrete theme from the host/OS-provided system theme, so the plugin
+    /// blends into light- and dark-skinned DAWs. `raw.system_theme` is set by the host before our
+    /// frame, unlike `window_fill`, which reflects the theme we last applied. When the host
+    /// reports nothing we keep whatever we last resolved. `Dark`/`Light` are returned unchanged.
+    fn resolve(self, ctx: &Context) -> ThemeType {
+        if self != ThemeType::Auto {
+            return self;
+        }
+
+        let id = Id::new("auto-theme-resolved");
+        let previous = ctx.data(|d| d.get_temp::<ThemeType>(id)).unwrap_or(ThemeType::Dark);
+
+        let resolved = match ctx.input(|i| i.raw.system_theme) {
+            Some(egui::Theme::Light) => ThemeType::Light,
+            Some(egui::Theme::Dark) => ThemeType::Dark,
+            None => previous,
+        };
+
+        ctx.data_mut(|d| d.insert_temp(id, resolved));
+        resolved
+    }
 }
 
 const ACCENT_COLOR: Color32 = Color32::from_rgb(0, 157, 255);
+/// Pixel tolerance for grabbing a selection edge to resize it
+const HANDLE_WIDTH: f32 = 4.0;
 const SECONDS_RANGE: RangeInclusive<f32> = 0.0..=60.0;
 const NOTE_VALUES: [Note; 6] = [
     Note(1, 4),
@@ -93,8 +136,8 @@ pub(crate) fn update_ui(ctx: &Context, setter: &ParamSetter, state: &EditorState
     ResizableWindow::new("res-wind")
         .min_size(Vec2::new(600.0, 120.0))
         .show(ctx, egui_state, |ui| {
-            let theme_type = params.theme_type.clone().load();
-            let factory = UiFactory { 
+            let theme_type = params.theme_type.clone().load().resolve(ctx);
+            let factory = UiFactory {
                 theme: theme_type.theme()
             };
 
@@ -111,7 +154,7 @@ pub(crate) fn update_ui(ctx: &Context, setter: &ParamSetter, state: &EditorState
                         // We don't want an extra gap between the train and the platform
                         ui.spacing_mut().item_spacing.y = 0.0;
 
-                        factory.top_bar(ui, params, setter);
+                        factory.top_bar(ui, params, setter, state);
                         factory.waveform_view(ui, state);
                     });
                 });
@@ -123,7 +166,7 @@ struct UiFactory {
 }
 
 impl UiFactory {
-    fn top_bar(&self, ui: &mut Ui, params: &RollingSamplerCloneParams, setter: &ParamSetter) {
+    fn top_bar(&self, ui: &mut Ui, params: &RollingSamplerCloneParams, setter: &ParamSetter, state: &EditorState) {
         Frame::new()
             .fill(self.theme.fg_color_primary)
             .inner_margin(Margin::symmetric(8, 4))
@@ -138,7 +181,23 @@ impl UiFactory {
             
                     self.buffer_size_picker(ui, params);
 
-                    ui.add(Button::new("Clear Buffer"));
+                    if ui.add(Button::new("Clear Buffer")).clicked() {
+                        state.clear_requested.store(true, Ordering::Release);
+                    }
+
+                    if ui.add(Button::new("Save Clip")).clicked() {
+                        state.save_requested.store(true, Ordering::Release);
+                    }
+
+                    let playing = state.playing_back.load(Ordering::Acquire);
+                    if ui.add(Button::new(if playing { "Stop" } else { "Play" })).clicked() {
+                        if playing {
+                            state.playing_back.store(false, Ordering::Release);
+                        } else {
+                            // Start auditioning from the current playhead position
+                            state.playing_back.store(true, Ordering::Release);
+                        }
+                    }
 
                     ui.separator();
 
@@ -148,8 +207,9 @@ impl UiFactory {
 
                     ui.menu_button("âš™", |ui| {
                         self.theme_picker(ui, params.theme_type.clone());
-                        self.checkbox(ui, "Reset on Play", params.clear_on_play.clone()); 
+                        self.checkbox(ui, "Reset on Play", params.clear_on_play.clone());
                         self.checkbox(ui, "Trim Silence", params.trim_silence.clone());
+                        self.clip_path_picker(ui, params.clip_path.clone());
                     });
                 });
             });
@@ -222,6 +282,8 @@ impl UiFactory {
                         self.text(ThemeType::Dark.to_string().as_str()));
                     ui.selectable_value(&mut selected, ThemeType::Light,
                         self.text(ThemeType::Light.to_string().as_str()));
+                    ui.selectable_value(&mut selected, ThemeType::Auto,
+                        self.text(ThemeType::Auto.to_string().as_str()));
                 });
             
             cell.store(selected);
@@ -238,6 +300,19 @@ impl UiFactory {
         });
     }
     
+    fn clip_path_picker(&self, ui: &mut Ui, cell: Arc<Mutex<PathBuf>>) {
+        ui.horizontal(|ui| {
+            ui.add(Label::new(self.text("Clip Folder")));
+
+            if ui.add(Button::new("…")).clicked() {
+                let current = cell.lock().unwrap().clone();
+                if let Some(dir) = rfd::FileDialog::new().set_directory(&current).pick_folder() {
+                    *cell.lock().unwrap() = dir;
+                }
+            }
+        });
+    }
+
     fn waveform_view(&self, ui: &mut Ui, state: &EditorState) {
         Frame::new()
             .fill(self.theme.fg_color_primary)
@@ -255,29 +330,128 @@ impl UiFactory {
 
                 let mut buffer = state.waveform_buffer_output.lock().unwrap();
                 self.draw_waveform(&response, &painter, start_pos, Vec2::new(width, max_amplitude), buffer.read(), Stroke::new(1.0, ACCENT_COLOR));
+
+                let ctx = ui.ctx().clone();
+                let rect = response.rect;
+                let frac = |x: f32| ((x - rect.min.x) / width).clamp(0.0, 1.0);
+                let anchor_id = Id::new("waveform-selection-anchor");
+
+                // Click-drag defines a region; dragging an existing edge resizes it
+                if response.drag_started() {
+                    if let Some(pos) = response.interact_pointer_pos() {
+                        let t = frac(pos.x);
+                        // Anchor the drag to the opposite edge when grabbing a handle, else `t`
+                        let anchor = if state.selection_active.load(Ordering::Acquire) {
+                            let start = state.selection_start.load(Ordering::Acquire);
+                            let end = state.selection_end.load(Ordering::Acquire);
+                            let start_x = rect.min.x + start * width;
+                            let end_x = rect.min.x + end * width;
+                            if (pos.x - start_x).abs() <= HANDLE_WIDTH {
+                                end
+                            } else if (pos.x - end_x).abs() <= HANDLE_WIDTH {
+                                start
+                            } else {
+                                t
+                            }
+                        } else {
+                            t
+                        };
+
+                        ctx.data_mut(|d| d.insert_temp(anchor_id, anchor));
+                        state.selection_start.store(anchor.min(t), Ordering::Release);
+                        state.selection_end.store(anchor.max(t), Ordering::Release);
+                        state.selection_active.store(true, Ordering::Release);
+                    }
+                } else if response.dragged() {
+                    if let Some(pos) = response.interact_pointer_pos() {
+                        let t = frac(pos.x);
+                        let anchor = ctx.data(|d| d.get_temp::<f32>(anchor_id)).unwrap_or(t);
+                        state.selection_start.store(anchor.min(t), Ordering::Release);
+                        state.selection_end.store(anchor.max(t), Ordering::Release);
+                    }
+                } else if response.clicked() {
+                    // A plain click repositions the playhead; only a click *outside* the
+                    // selected span clears it, so the user can move the playhead within it
+                    if let Some(pos) = response.interact_pointer_pos() {
+                        let t = frac(pos.x);
+                        if state.selection_active.load(Ordering::Acquire) {
+                            let start = state.selection_start.load(Ordering::Acquire);
+                            let end = state.selection_end.load(Ordering::Acquire);
+                            if t < start.min(end) || t > start.max(end) {
+                                state.selection_active.store(false, Ordering::Release);
+                            }
+                        }
+                        state.playhead.store(t, Ordering::Release);
+                    }
+                }
+
+                // Shade the selected span and draw resize handles at its edges
+                if state.selection_active.load(Ordering::Acquire) {
+                    let start = state.selection_start.load(Ordering::Acquire);
+                    let end = state.selection_end.load(Ordering::Acquire);
+                    let x0 = rect.min.x + start.min(end) * width;
+                    let x1 = rect.min.x + start.max(end) * width;
+
+                    let span = Rect::from_min_max(Pos2::new(x0, rect.min.y), Pos2::new(x1, rect.max.y));
+                    painter.rect_filled(span, 0.0, Color32::from_rgba_unmultiplied(0, 157, 255, 48));
+
+                    let handle = Stroke::new(2.0, ACCENT_COLOR);
+                    for x in [x0, x1] {
+                        painter.line_segment([Pos2::new(x, rect.min.y), Pos2::new(x, rect.max.y)], handle);
+                    }
+                }
+
+                // Draw the playhead line over the waveform
+                let playhead_x = rect.min.x + state.playhead.load(Ordering::Acquire) * width;
+                painter.line_segment(
+                    [Pos2::new(playhead_x, rect.min.y), Pos2::new(playhead_x, rect.max.y)],
+                    Stroke::new(1.0, self.theme.text_color));
             });
     }
 
     /// Draws a waveform from a collection of samples
     /// Start pos refers to the left-hand position of the equilibrium line
     /// Size refers to (width, amplitude)
-    fn draw_waveform(&self, response: &Response, painter: &Painter, start_pos: Vec2, size: Vec2, samples: &Vec<f32>, stroke: Stroke) {
-        // First and last elements are equal to the equilibrium position at their respective x coordinates
-        let mut vertices: Vec<Pos2> = Vec::new();
-        // Start pos
-        vertices.push(start_pos.to_pos2()); 
-
-        // Sample positions
-        for (i, sample) in samples.iter().enumerate() {
-            let t = (i as f32) / ((samples.len() - 1) as f32);
-            let x = emath::lerp(start_pos.x..=(start_pos.x + size.x), t);
-            vertices.push(Vec2::new(x, start_pos.y).to_pos2());
+    fn draw_waveform(&self, _response: &Response, painter: &Painter, start_pos: Vec2, size: Vec2, samples: &Vec<f32>, stroke: Stroke) {
+        if samples.is_empty() {
+            return;
         }
 
-        // End pos
-        vertices.push((start_pos + Vec2::new(size.x, 0.0)).to_pos2());
+        let (width, amplitude) = (size.x, size.y);
+        let columns = (width.max(1.0)) as usize;
+
+        if samples.len() >= columns {
+            // More samples than pixels: one vertical peak (min/max) bar per column
+            for col in 0..columns {
+                let i0 = col * samples.len() / columns;
+                let i1 = ((col + 1) * samples.len() / columns).max(i0 + 1);
+
+                let (mut min, mut max) = (f32::MAX, f32::MIN);
+                for sample in &samples[i0..i1] {
+                    min = min.min(*sample);
+                    max = max.max(*sample);
+                }
 
-        painter.line(vertices, stroke);
+                let x = start_pos.x + col as f32;
+                let top = start_pos.y - max * amplitude;
+                let bottom = start_pos.y - min * amplitude;
+                painter.line_segment([Pos2::new(x, top), Pos2::new(x, bottom)], stroke);
+            }
+        } else if samples.len() == 1 {
+            // A lone sample has no range to interpolate over, so draw it flat across the view
+            let y = start_pos.y - samples[0] * amplitude;
+            painter.line_segment([Pos2::new(start_pos.x, y), Pos2::new(start_pos.x + width, y)], stroke);
+        } else {
+            // Fewer samples than pixels: plot each sample and connect the dots
+            let mut vertices: Vec<Pos2> = Vec::with_capacity(samples.len());
+            for (i, sample) in samples.iter().enumerate() {
+                let t = (i as f32) / ((samples.len() - 1) as f32);
+                let x = emath::lerp(start_pos.x..=(start_pos.x + width), t);
+                let y = start_pos.y - sample * amplitude;
+                vertices.push(Pos2::new(x, y));
+            }
+            painter.line(vertices, stroke);
+        }
     }
 
     fn text(&self, text: &str) -> RichText {
@@ -1,11 +1,25 @@
-use std::sync::{Arc, Mutex, atomic::AtomicBool};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
 
 use crossbeam::atomic::AtomicCell;
 use nih_plug::prelude::*;
 use nih_plug_egui::{EguiState, create_egui_editor};
 use triple_buffer::{self, TripleBuffer};
 
-use crate::{buffer_size::Note, editor::EditorState};
+use crate::{buffer_size::{BufferSizeUnit, Note}, editor::EditorState};
+
+/// A snapshot of the ring buffer handed to the async executor to be encoded and written to
+/// disk. Carried as a [`Plugin::BackgroundTask`] so the file I/O never runs on the audio thread.
+pub struct SaveClip {
+    samples: Vec<Vec<f32>>,
+    sample_rate: u32,
+    trim_silence: bool,
+}
+
+/// Maximum number of min/max buckets published to the UI per block (two values each). The
+/// rolling buffer itself can hold millions of samples, so we decimate down to a fixed resolution
+/// before handing it across the triple buffer to keep the per-block copy cheap.
+const WAVEFORM_RESOLUTION: usize = 2048;
 
 mod buffer_size;
 mod dir;
@@ -18,9 +32,37 @@ pub struct RollingSamplerClone {
     waveform_buffer_input: triple_buffer::Input<Vec<f32>>,
     /// We pass this to the UI so it can read the samples
     waveform_buffer_output: Arc<Mutex<triple_buffer::Output<Vec<f32>>>>,
-    /// This is where we actually store the samples
+    /// This is where we actually store the samples. One ring buffer per channel, each of
+    /// length N, always holding the most recent N samples (zero-padded until it first fills).
     recording_buffer: Vec<Vec<f32>>,
+    /// Index the next incoming sample will be written to; shared across all channels.
+    write_pos: usize,
+    /// Incremental peak pyramid of the mono mixdown, kept so the UI waveform can be published
+    /// cheaply: `peaks` holds one min/max summary per bucket of `peak_factor` samples, rolled in
+    /// lockstep with the ring. `peak_pos` is the bucket currently being filled, `peak_count` how
+    /// many samples it has so far. This avoids rescanning the whole ring on the audio thread.
+    peaks: Vec<(f32, f32)>,
+    peak_pos: usize,
+    peak_count: usize,
+    peak_factor: usize,
+    /// Host sample rate, captured in `initialize`, used to size the ring buffer.
+    sample_rate: f32,
+    /// Previous transport play state, so we can detect the rising edge for `clear_on_play`.
+    was_playing: bool,
     buffer_size_invalidated: Arc<AtomicBool>,
+    /// Set by the editor's "Save Clip" action; the audio thread snapshots and dispatches.
+    save_requested: Arc<AtomicBool>,
+    /// Whether the stored buffer is currently being auditioned through the output.
+    playing_back: Arc<AtomicBool>,
+    /// Normalized (0..1) audition position, shared with the editor for the playhead line.
+    playhead: Arc<AtomicF32>,
+    /// Set by the editor's "Clear Buffer" action; zeroes the buffer (or selection) next block.
+    clear_requested: Arc<AtomicBool>,
+    /// Whether a region of the buffer is selected. When set, clear/save act on the selection.
+    selection_active: Arc<AtomicBool>,
+    /// Normalized (0..1) selection bounds, shared with the editor.
+    selection_start: Arc<AtomicF32>,
+    selection_end: Arc<AtomicF32>,
     parent_handle: Arc<AtomicCell<Option<ParentWindowHandle>>>
 }
 
@@ -46,9 +88,9 @@ pub struct RollingSamplerCloneParams {
     #[persist = "trim-silence"]
     trim_silence: Arc<AtomicBool>,
 
-    // / Directory where saved clips are stored
-    // #[persist = "clip_path"]
-    // clip_path: Arc<Mutex<PathBuf>>
+    /// Directory where saved clips are stored
+    #[persist = "clip-path"]
+    clip_path: Arc<Mutex<PathBuf>>,
 }
 
 impl Default for RollingSamplerCloneParams {
@@ -62,7 +104,7 @@ impl Default for RollingSamplerCloneParams {
             theme_type: Arc::new(AtomicCell::new(editor::ThemeType::Dark)),
             clear_on_play: Arc::new(AtomicBool::new(false)),
             trim_silence: Arc::new(AtomicBool::new(true)),
-            // clip_path: Arc::new(Mutex::new(dir::default_clip_dir()))
+            clip_path: Arc::new(Mutex::new(dir::default_clip_dir())),
         }
     }
 }
@@ -77,12 +119,150 @@ impl Default for RollingSamplerClone {
             waveform_buffer_input: input,
             waveform_buffer_output: Arc::new(Mutex::new(output)),
             recording_buffer: Vec::new(),
+            write_pos: 0,
+            peaks: Vec::new(),
+            peak_pos: 0,
+            peak_count: 0,
+            peak_factor: 1,
+            sample_rate: 44100.0,
+            was_playing: false,
             buffer_size_invalidated: Arc::new(AtomicBool::new(false)),
+            save_requested: Arc::new(AtomicBool::new(false)),
+            playing_back: Arc::new(AtomicBool::new(false)),
+            playhead: Arc::new(AtomicF32::new(0.0)),
+            clear_requested: Arc::new(AtomicBool::new(false)),
+            selection_active: Arc::new(AtomicBool::new(false)),
+            selection_start: Arc::new(AtomicF32::new(0.0)),
+            selection_end: Arc::new(AtomicF32::new(0.0)),
             parent_handle: Arc::new(AtomicCell::new(None)),
         }
     }
 }
 
+impl RollingSamplerClone {
+    /// Number of samples the ring buffer should currently hold, derived from the `BufferSize`
+    /// param. For note units this depends on the host tempo, so the caller passes it in.
+    fn target_buffer_len(&self, tempo: f32) -> usize {
+        let size = &self.params.buffer_size;
+        let n = match size.unit.load() {
+            BufferSizeUnit::Seconds => size.seconds.load(Ordering::Acquire) * self.sample_rate,
+            BufferSizeUnit::Notes => {
+                let note = size.notes.load();
+                // A Note(num, den) is that fraction of a whole note, and a whole note is 4 beats
+                let beats = 4.0 * note.0 as f32 / note.1 as f32;
+                beats * (60.0 / tempo) * self.sample_rate
+            }
+        };
+
+        (n.round() as usize).max(1)
+    }
+
+    /// Reallocates every channel's ring to `new_len`, preserving the most recent audio so a
+    /// size change doesn't glitch. The retained tail is aligned to the end of the new ring and
+    /// `write_pos` is reset so index 0 is once again the oldest sample.
+    fn resize_ring(&mut self, channels: usize, new_len: usize) {
+        let old_len = self.recording_buffer.first().map_or(0, Vec::len);
+        let mut resized: Vec<Vec<f32>> = Vec::with_capacity(channels);
+
+        for c in 0..channels {
+            let mut chan = vec![0.0f32; new_len];
+
+            if let (Some(existing), true) = (self.recording_buffer.get(c), old_len > 0) {
+                // Walk the old ring oldest-first, then keep only the most recent `new_len`
+                let ordered: Vec<f32> = (0..old_len)
+                    .map(|i| existing[(self.write_pos + i) % old_len])
+                    .collect();
+                let keep = ordered.len().min(new_len);
+                chan[new_len - keep..].copy_from_slice(&ordered[ordered.len() - keep..]);
+            }
+
+            resized.push(chan);
+        }
+
+        self.recording_buffer = resized;
+        self.write_pos = 0;
+
+        // Rebuild the peak pyramid to match the new length; it refills as audio rolls in
+        self.peak_factor = new_len.div_ceil(WAVEFORM_RESOLUTION).max(1);
+        let buckets = new_len.div_ceil(self.peak_factor).max(1);
+        self.peaks = vec![(0.0, 0.0); buckets];
+        self.peak_pos = 0;
+        self.peak_count = 0;
+    }
+
+    /// Folds one mono sample into the peak pyramid in O(1), advancing to the next bucket once the
+    /// current one has collected `peak_factor` samples.
+    fn push_peak(&mut self, sample: f32) {
+        if self.peaks.is_empty() {
+            return;
+        }
+
+        let bucket = &mut self.peaks[self.peak_pos];
+        if self.peak_count == 0 {
+            *bucket = (sample, sample);
+        } else {
+            bucket.0 = bucket.0.min(sample);
+            bucket.1 = bucket.1.max(sample);
+        }
+
+        self.peak_count += 1;
+        if self.peak_count >= self.peak_factor {
+            self.peak_count = 0;
+            self.peak_pos = (self.peak_pos + 1) % self.peaks.len();
+        }
+    }
+
+    /// Copies the chronological range `[i0, i1)` of the ring buffer out (oldest sample first),
+    /// one inner vec per channel, ready to be encoded to disk.
+    fn snapshot(&self, i0: usize, i1: usize) -> Vec<Vec<f32>> {
+        let ring_len = self.recording_buffer.first().map_or(0, Vec::len);
+        if ring_len == 0 {
+            return Vec::new();
+        }
+        self.recording_buffer
+            .iter()
+            .map(|chan| (i0..i1).map(|i| chan[(self.write_pos + i) % ring_len]).collect())
+            .collect()
+    }
+
+    /// The chronological frame range the current action applies to: the selection if one is
+    /// active, otherwise the whole buffer.
+    fn selection_frames(&self, ring_len: usize) -> (usize, usize) {
+        if !self.selection_active.load(Ordering::Acquire) {
+            return (0, ring_len);
+        }
+
+        let start = self.selection_start.load(Ordering::Acquire);
+        let end = self.selection_end.load(Ordering::Acquire);
+        let i0 = (start.min(end) * ring_len as f32).round() as usize;
+        let i1 = (start.max(end) * ring_len as f32).round() as usize;
+        (i0.min(ring_len), i1.min(ring_len))
+    }
+
+    /// Publishes the peak pyramid to the UI as interleaved min/max values, oldest bucket first.
+    /// This is O(buckets) and writes in place into the triple buffer's slot, so no per-block
+    /// allocation or full ring scan happens on the audio thread.
+    fn publish_waveform(&mut self) {
+        let buckets = self.peaks.len();
+        let out = self.waveform_buffer_input.input_buffer();
+        out.clear();
+
+        if buckets == 0 {
+            self.waveform_buffer_input.publish();
+            return;
+        }
+
+        // The bucket currently being filled is the newest; the one just after it is the oldest
+        for k in 1..=buckets {
+            let (min, max) = self.peaks[(self.peak_pos + k) % buckets];
+            out.push(min);
+            out.push(max);
+        }
+
+        self.waveform_buffer_input.publish();
+    }
+}
+
 impl Plugin for RollingSamplerClone {
     const NAME: &'static str = "Rolling Sampler Clone";
     const VENDOR: &'static str = "danmarkowal";
@@ -109,20 +289,139 @@ impl Plugin for RollingSamplerClone {
     ];
 
     type SysExMessage = ();
-    type BackgroundTask = ();
+    type BackgroundTask = SaveClip;
+
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        // The executor runs off the audio thread, so it's safe to do the clip encode/write here
+        let clip_path = self.params.clip_path.clone();
+        Box::new(move |task: SaveClip| {
+            let dir = clip_path.lock().unwrap().clone();
+            dir::save_clip(&dir, task.samples, task.sample_rate, task.trim_silence);
+        })
+    }
 
     fn params(&self) -> std::sync::Arc<dyn Params> {
         self.params.clone()
     }
 
+    fn initialize(
+        &mut self,
+        _audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.sample_rate = buffer_config.sample_rate;
+        // Force a (re)allocation on the first block now that we know the sample rate
+        self.buffer_size_invalidated.store(true, Ordering::Release);
+        true
+    }
+
     fn process(
         &mut self,
         buffer: &mut Buffer,
         _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        for channel_samples in buffer.iter_samples() {
-            let samples: Vec<f32> = channel_samples.into_iter().map(|x| x.to_f32()).collect();
+        let channels = buffer.channels();
+
+        let (playing, tempo) = {
+            let transport = context.transport();
+            (transport.playing, transport.tempo.unwrap_or(120.0) as f32)
+        };
+
+        // Zero the ring on the rising edge of playback when the user asked us to
+        if self.params.clear_on_play.load(Ordering::Acquire) && playing && !self.was_playing {
+            for chan in self.recording_buffer.iter_mut() {
+                chan.iter_mut().for_each(|s| *s = 0.0);
+            }
+            self.peaks.iter_mut().for_each(|p| *p = (0.0, 0.0));
+        }
+        self.was_playing = playing;
+
+        // Honor a clear request, scoped to the selection when one is active
+        if self.clear_requested.swap(false, Ordering::AcqRel) {
+            let ring_len = self.recording_buffer.first().map_or(0, Vec::len);
+            if ring_len > 0 {
+                let (i0, i1) = self.selection_frames(ring_len);
+                let write_pos = self.write_pos;
+                for chan in self.recording_buffer.iter_mut() {
+                    for i in i0..i1 {
+                        chan[(write_pos + i) % ring_len] = 0.0;
+                    }
+                }
+
+                // Zero the matching peak buckets (oldest bucket follows `peak_pos`) so the UI agrees
+                let buckets = self.peaks.len();
+                let (b0, b1) = (i0 * buckets / ring_len, (i1 * buckets).div_ceil(ring_len));
+                for j in b0..b1 {
+                    self.peaks[(self.peak_pos + 1 + j) % buckets] = (0.0, 0.0);
+                }
+            }
+        }
+
+        let target_len = self.target_buffer_len(tempo);
+        let invalidated = self.buffer_size_invalidated.swap(false, Ordering::AcqRel);
+        let needs_resize = invalidated
+            || self.recording_buffer.len() != channels
+            || self.recording_buffer.first().map_or(true, |c| c.len() != target_len);
+        if needs_resize {
+            self.resize_ring(channels, target_len);
+        }
+
+        // Recording is frozen while auditioning so the captured phrase isn't overwritten under
+        // the playhead and the read cursor can advance 1:1 through a stationary window
+        let auditioning = self.playing_back.load(Ordering::Acquire);
+
+        let ring_len = self.recording_buffer.first().map_or(0, Vec::len);
+        if ring_len > 0 && !auditioning {
+            let scale = 1.0 / channels as f32;
+            for channel_samples in buffer.iter_samples() {
+                let mut mono = 0.0;
+                for (c, sample) in channel_samples.into_iter().enumerate() {
+                    if let Some(chan) = self.recording_buffer.get_mut(c) {
+                        chan[self.write_pos] = *sample;
+                    }
+                    mono += *sample;
+                }
+                self.push_peak(mono * scale);
+                self.write_pos = (self.write_pos + 1) % ring_len;
+            }
+        }
+
+        // Audition: mix the stored buffer into the output from the playhead onwards
+        if ring_len > 0 && auditioning {
+            let rec_channels = self.recording_buffer.len();
+            let mut pos = (self.playhead.load(Ordering::Acquire) * ring_len as f32).round() as usize;
+
+            for channel_samples in buffer.iter_samples() {
+                if pos >= ring_len {
+                    break;
+                }
+                for (c, sample) in channel_samples.into_iter().enumerate() {
+                    // Fold extra output channels back onto the last recorded one (mono <-> stereo)
+                    let chan = &self.recording_buffer[c.min(rec_channels - 1)];
+                    *sample += chan[(self.write_pos + pos) % ring_len];
+                }
+                pos += 1;
+            }
+
+            if pos >= ring_len {
+                self.playing_back.store(false, Ordering::Release);
+            }
+            self.playhead.store((pos as f32 / ring_len as f32).min(1.0), Ordering::Release);
+        }
+
+        // Let the UI see the live rolling contents (cheap O(buckets) publish, no allocation)
+        self.publish_waveform();
+
+        // Snapshot and dispatch a save off the audio thread if the editor asked for one
+        if ring_len > 0 && self.save_requested.swap(false, Ordering::AcqRel) {
+            let (i0, i1) = self.selection_frames(ring_len);
+            context.execute_background(SaveClip {
+                samples: self.snapshot(i0, i1),
+                sample_rate: self.sample_rate as u32,
+                trim_silence: self.params.trim_silence.load(Ordering::Acquire),
+            });
         }
 
         ProcessStatus::Normal
@@ -139,7 +438,14 @@ impl Plugin for RollingSamplerClone {
             // This clone lives for as long as the editor exists
             editor_state,
             EditorState {
-                waveform_buffer_output: self.waveform_buffer_output.clone()
+                waveform_buffer_output: self.waveform_buffer_output.clone(),
+                save_requested: self.save_requested.clone(),
+                playing_back: self.playing_back.clone(),
+                playhead: self.playhead.clone(),
+                clear_requested: self.clear_requested.clone(),
+                selection_active: self.selection_active.clone(),
+                selection_start: self.selection_start.clone(),
+                selection_end: self.selection_end.clone(),
             },
             |ctx, _| {
                 editor::build_ui(ctx);